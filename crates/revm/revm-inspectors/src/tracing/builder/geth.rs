@@ -1,6 +1,6 @@
 //! Geth trace builder
 
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 
 use revm::{
     db::DatabaseRef,
@@ -8,9 +8,12 @@ use revm::{
 };
 
 use reth_primitives::{Address, Bytes, H256, U256};
-use reth_rpc_types::trace::geth::{
-    AccountState, CallConfig, CallFrame, ChangeType, DefaultFrame, DiffMode,
-    GethDefaultTracingOptions, PreStateConfig, PreStateFrame, PreStateMode, StructLog,
+use reth_rpc_types::trace::{
+    geth::{
+        AccountState, CallConfig, CallFrame, ChangeType, DefaultFrame, DiffMode,
+        GethDefaultTracingOptions, PreStateConfig, PreStateFrame, PreStateMode, StructLog,
+    },
+    parity::{AccountDiff, ChangedType, Diff, StateDiff},
 };
 
 use crate::tracing::{
@@ -184,91 +187,182 @@ impl GethTraceBuilder {
     /// * `db` - The database to fetch state pre-transaction execution.
     pub fn geth_prestate_traces<DB>(
         &self,
-        ResultAndState { state, .. }: &ResultAndState,
+        res: &ResultAndState,
         prestate_config: PreStateConfig,
         db: DB,
     ) -> Result<PreStateFrame, DB::Error>
     where
         DB: DatabaseRef,
     {
-        let account_diffs: Vec<_> = state.into_iter().map(|(addr, acc)| (*addr, acc)).collect();
-        let is_diff = prestate_config.is_diff_mode();
-        if !is_diff {
-            let mut prestate = PreStateMode::default();
-            for (addr, _) in account_diffs {
-                let db_acc = db.basic(addr)?.unwrap_or_default();
-                prestate.0.insert(
-                    addr,
-                    AccountState {
-                        balance: Some(db_acc.balance),
-                        nonce: Some(db_acc.nonce),
-                        code: db_acc.code.as_ref().map(|code| Bytes::from(code.original_bytes())),
-                        storage: None,
-                        change_type: ChangeType::Modify,
-                    },
-                );
-            }
-            self.update_storage_from_trace_prestate_mode(&mut prestate.0, false);
-            Ok(PreStateFrame::Default(prestate))
+        if prestate_config.is_diff_mode() {
+            self.geth_prestate_diff_traces(res, prestate_config, db).map(PreStateFrame::Diff)
         } else {
-            let mut state_diff = DiffMode::default();
-            for (addr, changed_acc) in account_diffs {
-                let db_acc = db.basic(addr)?.unwrap_or_default();
-                let db_code = db_acc.code.as_ref();
-                let db_code_hash = db_acc.code_hash;
+            self.geth_prestate_pre_traces(res, prestate_config, db).map(PreStateFrame::Default)
+        }
+    }
 
-                // Geth always includes the contract code in the prestate. However,
-                // the code hash will be KECCAK_EMPTY if the account is an EOA. Therefore
-                // we need to filter it out.
-                let pre_code =
-                    db_code.map(|code| Bytes::from(code.original_bytes())).or_else(|| {
-                        if db_code_hash == KECCAK_EMPTY {
-                            None
-                        } else {
-                            db.code_by_hash(db_code_hash)
-                                .ok()
-                                .map(|code| Bytes::from(code.original_bytes()))
-                        }
-                    });
-
-                // Contract code can come back as a zero-length byte array. This shouldn't
-                // show up in the state diff, so we filter it out below.
-                let pre_state = AccountState {
+    /// Generates the geth-style prestate trace in prestate (non-diff) mode, e.g. for
+    /// `debug_traceTransaction` with `"tracer": "prestateTracer"`.
+    ///
+    /// Honors `disableCode`/`disableStorage` on the given [PreStateConfig]: when either is
+    /// disabled we skip the corresponding (potentially expensive) lookup entirely, matching
+    /// geth's prestate tracer options.
+    pub fn geth_prestate_pre_traces<DB>(
+        &self,
+        ResultAndState { state, .. }: &ResultAndState,
+        prestate_config: PreStateConfig,
+        db: DB,
+    ) -> Result<PreStateMode, DB::Error>
+    where
+        DB: DatabaseRef,
+    {
+        let mut prestate = PreStateMode::default();
+        for (addr, _) in state.iter() {
+            let db_acc = db.basic(*addr)?.unwrap_or_default();
+            prestate.0.insert(
+                *addr,
+                AccountState {
                     balance: Some(db_acc.balance),
                     nonce: Some(db_acc.nonce),
-                    code: pre_code.filter(|code| !code.is_empty()),
-                    storage: None,
-                    change_type: if db_acc.is_empty() {
-                        ChangeType::Create
+                    code: if code_enabled(&prestate_config) {
+                        db_acc.code.as_ref().map(|code| Bytes::from(code.original_bytes()))
                     } else {
-                        ChangeType::Modify
+                        None
                     },
-                };
-
-                let post_state = AccountState {
-                    balance: Some(changed_acc.info.balance),
-                    nonce: Some(changed_acc.info.nonce),
-                    code: changed_acc
-                        .info
-                        .code
-                        .as_ref()
-                        .filter(|code| !code.is_empty())
-                        .map(|code| Bytes::from(code.original_bytes())),
                     storage: None,
-                    change_type: if changed_acc.is_destroyed {
-                        ChangeType::Destroy
+                    change_type: ChangeType::Modify,
+                },
+            );
+        }
+
+        if storage_enabled(&prestate_config) {
+            self.update_storage_from_trace_prestate_mode(&mut prestate.0, false);
+        }
+
+        Ok(prestate)
+    }
+
+    /// Generates the geth-style prestate trace in diff mode, e.g. for `debug_traceTransaction`
+    /// with `"tracer": "prestateTracer", "tracerConfig": {"diffMode": true}`.
+    ///
+    /// Honors `disableCode`/`disableStorage` on the given [PreStateConfig], see
+    /// [Self::geth_prestate_pre_traces].
+    pub fn geth_prestate_diff_traces<DB>(
+        &self,
+        res: &ResultAndState,
+        prestate_config: PreStateConfig,
+        db: DB,
+    ) -> Result<DiffMode, DB::Error>
+    where
+        DB: DatabaseRef,
+    {
+        let (pre, post) = self.account_diffs(res, &prestate_config, db)?;
+        Ok(self.diff_traces(&pre, &post))
+    }
+
+    /// Generates the Parity (OpenEthereum) style `stateDiff` for this transaction, e.g. for
+    /// `trace_replayTransaction`.
+    ///
+    /// This reuses the same pre/post [AccountState] maps that [Self::geth_prestate_diff_traces]
+    /// assembles, but instead of stripping unchanged fields to `None` it tags every changed field
+    /// with Parity's `"+"`/`"-"`/`"*"` encoding.
+    pub fn parity_state_diff<DB>(
+        &self,
+        res: &ResultAndState,
+        db: DB,
+    ) -> Result<StateDiff, DB::Error>
+    where
+        DB: DatabaseRef,
+    {
+        let (pre, post) = self.account_diffs(res, &PreStateConfig::default(), db)?;
+        Ok(self.parity_diff_traces(&pre, &post))
+    }
+
+    /// Assembles the pre- and post-transaction [AccountState] maps for every account touched by
+    /// the transaction, honoring `disableCode`/`disableStorage` on the given [PreStateConfig].
+    /// This is the shared basis for both geth's diff-mode prestate tracer and Parity's
+    /// `stateDiff` output.
+    fn account_diffs<DB>(
+        &self,
+        ResultAndState { state, .. }: &ResultAndState,
+        prestate_config: &PreStateConfig,
+        db: DB,
+    ) -> Result<(BTreeMap<Address, AccountState>, BTreeMap<Address, AccountState>), DB::Error>
+    where
+        DB: DatabaseRef,
+    {
+        let account_diffs: Vec<_> = state.into_iter().map(|(addr, acc)| (*addr, acc)).collect();
+        let mut pre = BTreeMap::new();
+        let mut post = BTreeMap::new();
+        for (addr, changed_acc) in account_diffs {
+            let db_acc = db.basic(addr)?.unwrap_or_default();
+
+            // Geth always includes the contract code in the prestate. However,
+            // the code hash will be KECCAK_EMPTY if the account is an EOA. Therefore
+            // we need to filter it out. If code is disabled entirely, skip the lookup.
+            let pre_code = if code_enabled(prestate_config) {
+                let db_code = db_acc.code.as_ref();
+                let db_code_hash = db_acc.code_hash;
+                db_code.map(|code| Bytes::from(code.original_bytes())).or_else(|| {
+                    if db_code_hash == KECCAK_EMPTY {
+                        None
                     } else {
-                        ChangeType::Modify
-                    },
-                };
+                        db.code_by_hash(db_code_hash)
+                            .ok()
+                            .map(|code| Bytes::from(code.original_bytes()))
+                    }
+                })
+            } else {
+                None
+            };
+
+            // Contract code can come back as a zero-length byte array. This shouldn't
+            // show up in the state diff, so we filter it out below.
+            let pre_state = AccountState {
+                balance: Some(db_acc.balance),
+                nonce: Some(db_acc.nonce),
+                code: pre_code.filter(|code| !code.is_empty()),
+                storage: None,
+                change_type: if db_acc.is_empty() {
+                    ChangeType::Create
+                } else {
+                    ChangeType::Modify
+                },
+            };
+
+            let post_code = if code_enabled(prestate_config) {
+                changed_acc
+                    .info
+                    .code
+                    .as_ref()
+                    .filter(|code| !code.is_empty())
+                    .map(|code| Bytes::from(code.original_bytes()))
+            } else {
+                None
+            };
+
+            let post_state = AccountState {
+                balance: Some(changed_acc.info.balance),
+                nonce: Some(changed_acc.info.nonce),
+                code: post_code,
+                storage: None,
+                change_type: if changed_acc.is_destroyed {
+                    ChangeType::Destroy
+                } else {
+                    ChangeType::Modify
+                },
+            };
+
+            post.insert(addr, post_state);
+            pre.insert(addr, pre_state);
+        }
 
-                state_diff.post.insert(addr, post_state);
-                state_diff.pre.insert(addr, pre_state);
-            }
-            self.update_storage_from_trace_diff_mode(&mut state_diff.pre, false);
-            self.update_storage_from_trace_diff_mode(&mut state_diff.post, true);
-            Ok(PreStateFrame::Diff(self.diff_traces(&state_diff.pre, &state_diff.post)))
+        if storage_enabled(prestate_config) {
+            self.update_storage_from_trace_diff_mode(&mut pre, false);
+            self.update_storage_from_trace_diff_mode(&mut post, true);
         }
+
+        Ok((pre, post))
     }
 
     fn update_storage_from_trace_prestate_mode(
@@ -347,4 +441,150 @@ impl GethTraceBuilder {
 
         out_diff
     }
+
+    /// Folds the pre- and post-transaction [AccountState] maps into a Parity style [StateDiff],
+    /// tagging every account and field with `"+"` (created), `"-"` (destroyed), or `"*"`
+    /// (modified). Unchanged accounts and storage slots are omitted; an unchanged balance, nonce
+    /// or code of a modified account is tagged [Diff::Same].
+    fn parity_diff_traces(
+        &self,
+        pre: &BTreeMap<Address, AccountState>,
+        post: &BTreeMap<Address, AccountState>,
+    ) -> StateDiff {
+        let mut state_diff = StateDiff::default();
+
+        let addrs: BTreeSet<Address> = pre.keys().chain(post.keys()).copied().collect();
+        for addr in addrs {
+            let pre_state = pre.get(&addr).cloned().unwrap_or_default();
+            let post_state = post.get(&addr).cloned().unwrap_or_default();
+            if pre_state == post_state {
+                continue
+            }
+
+            let is_created = pre_state.change_type == ChangeType::Create;
+            let is_destroyed = post_state.change_type == ChangeType::Destroy;
+
+            // An account created and destroyed within the same transaction never existed from
+            // an outside observer's point of view, so it's omitted entirely here too, mirroring
+            // `diff_traces` above (which drops it from both `pre` and `post`).
+            if is_created && is_destroyed {
+                continue
+            }
+
+            let balance = Self::field_diff(
+                is_created,
+                is_destroyed,
+                pre_state.balance.unwrap_or_default(),
+                post_state.balance.unwrap_or_default(),
+            );
+            let nonce = Self::field_diff(
+                is_created,
+                is_destroyed,
+                pre_state.nonce.unwrap_or_default(),
+                post_state.nonce.unwrap_or_default(),
+            );
+            let code = Self::field_diff(
+                is_created,
+                is_destroyed,
+                pre_state.code.clone().unwrap_or_default(),
+                post_state.code.clone().unwrap_or_default(),
+            );
+
+            let mut storage = BTreeMap::new();
+            let pre_storage = pre_state.storage.clone().unwrap_or_default();
+            let post_storage = post_state.storage.clone().unwrap_or_default();
+            let slots: BTreeSet<H256> =
+                pre_storage.keys().chain(post_storage.keys()).copied().collect();
+            for slot in slots {
+                let pre_value = pre_storage.get(&slot).copied().unwrap_or_default();
+                let post_value = post_storage.get(&slot).copied().unwrap_or_default();
+                if pre_value == post_value {
+                    continue
+                }
+                storage
+                    .insert(slot, Self::field_diff(is_created, is_destroyed, pre_value, post_value));
+            }
+
+            state_diff.0.insert(addr, AccountDiff { balance, nonce, code, storage });
+        }
+
+        state_diff
+    }
+
+    /// Tags a single pre/post field pair with Parity's `Diff` encoding.
+    fn field_diff<T: PartialEq>(is_created: bool, is_destroyed: bool, pre: T, post: T) -> Diff<T> {
+        if is_created {
+            Diff::Born(post)
+        } else if is_destroyed {
+            Diff::Died(pre)
+        } else if pre == post {
+            Diff::Same
+        } else {
+            Diff::Changed(ChangedType { from: pre, to: post })
+        }
+    }
+}
+
+/// Returns whether contract code should be included in the prestate output, mirroring geth's
+/// `disableCode` prestate tracer option.
+fn code_enabled(prestate_config: &PreStateConfig) -> bool {
+    !prestate_config.disable_code.unwrap_or_default()
+}
+
+/// Returns whether storage should be included in the prestate output, mirroring geth's
+/// `disableStorage` prestate tracer option.
+fn storage_enabled(prestate_config: &PreStateConfig) -> bool {
+    !prestate_config.disable_storage.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracing::TracingInspectorConfig;
+
+    #[test]
+    fn code_and_storage_enabled_respect_disable_flags() {
+        let mut cfg = PreStateConfig::default();
+        assert!(code_enabled(&cfg));
+        assert!(storage_enabled(&cfg));
+
+        cfg.disable_code = Some(true);
+        cfg.disable_storage = Some(true);
+        assert!(!code_enabled(&cfg));
+        assert!(!storage_enabled(&cfg));
+    }
+
+    #[test]
+    fn field_diff_tags_created_destroyed_and_changed() {
+        assert_eq!(GethTraceBuilder::field_diff(true, false, 1u64, 2u64), Diff::Born(2));
+        assert_eq!(GethTraceBuilder::field_diff(false, true, 1u64, 2u64), Diff::Died(1));
+        assert_eq!(GethTraceBuilder::field_diff(false, false, 1u64, 1u64), Diff::Same);
+        assert_eq!(
+            GethTraceBuilder::field_diff(false, false, 1u64, 2u64),
+            Diff::Changed(ChangedType { from: 1, to: 2 })
+        );
+    }
+
+    fn account(change_type: ChangeType) -> AccountState {
+        AccountState {
+            balance: Some(U256::from(1)),
+            nonce: Some(1),
+            code: None,
+            storage: None,
+            change_type,
+        }
+    }
+
+    #[test]
+    fn parity_state_diff_omits_accounts_created_and_destroyed_in_the_same_tx() {
+        let addr = Address::from_low_u64_be(1);
+        let mut pre = BTreeMap::new();
+        pre.insert(addr, account(ChangeType::Create));
+        let mut post = BTreeMap::new();
+        post.insert(addr, account(ChangeType::Destroy));
+
+        let builder = GethTraceBuilder::new(Vec::new(), TracingInspectorConfig::default());
+        let state_diff = builder.parity_diff_traces(&pre, &post);
+        assert!(!state_diff.0.contains_key(&addr));
+    }
 }
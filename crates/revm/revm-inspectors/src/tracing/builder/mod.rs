@@ -0,0 +1,4 @@
+//! Trace builders for converting a recorded call trace arena into different trace formats.
+
+pub mod geth;
+pub mod parity;
@@ -0,0 +1,367 @@
+//! Parity trace builder
+
+use std::collections::{HashMap, HashSet};
+
+use reth_primitives::{
+    bloom::{Bloom, BloomInput},
+    Address,
+};
+use reth_rpc_types::trace::{
+    geth::CallFrame,
+    parity::{
+        Action, CallAction, CallOutput, CallType, CreateAction, CreateOutput, SelfdestructAction,
+        TraceOutput, TransactionTrace,
+    },
+};
+
+use crate::tracing::{types::CallTraceNode, TracingInspectorConfig};
+
+/// A type for creating Parity style traces
+///
+/// Takes a `Vec` of [CallTraceNode]s and produces the flat [TransactionTrace] records used by
+/// OpenEthereum's `trace_transaction`/`trace_block` endpoints, without re-running the EVM.
+#[derive(Clone, Debug)]
+pub struct ParityTraceBuilder {
+    /// Recorded trace nodes, stored parent-before-child.
+    nodes: Vec<CallTraceNode>,
+    /// How the traces were recorded
+    _config: TracingInspectorConfig,
+}
+
+impl ParityTraceBuilder {
+    /// Returns a new instance of the builder
+    pub(crate) fn new(nodes: Vec<CallTraceNode>, _config: TracingInspectorConfig) -> Self {
+        Self { nodes, _config }
+    }
+
+    /// Returns the number of direct subcalls recorded for every node, keyed by their index in
+    /// the arena. Selfdestructs are derived, not recorded as nodes, so they aren't counted here;
+    /// [Self::parity_traces] accounts for them separately when assigning trace addresses.
+    fn subcall_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0usize; self.nodes.len()];
+        for node in self.nodes.iter().skip(1) {
+            if let Some(parent) = node.parent {
+                counts[parent] += 1;
+            }
+        }
+        counts
+    }
+
+    /// Returns the Parity style flat traces for this transaction, e.g. for
+    /// `trace_transaction`/`trace_block`.
+    ///
+    /// This walks the recorded call arena depth-first; because the arena already stores nodes
+    /// parent-before-child, iterating it in order is equivalent to the walk. Each node's
+    /// `trace_address` is its parent's `trace_address` with the node's ordinal among its
+    /// parent's direct subcalls appended; the root's `trace_address` is empty.
+    pub fn parity_traces(&self) -> Vec<TransactionTrace> {
+        if self.nodes.is_empty() {
+            return Vec::new()
+        }
+
+        let total_subcalls = self.subcall_counts();
+        let mut trace_addresses = vec![Vec::new(); self.nodes.len()];
+        // Ordinal of the next direct subcall to be assigned, per parent index.
+        let mut next_ordinal = HashMap::with_capacity(self.nodes.len());
+
+        let mut traces = Vec::with_capacity(self.nodes.len());
+
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if let Some(parent) = node.parent {
+                let ordinal = next_ordinal.entry(parent).or_insert(0usize);
+                let mut address = trace_addresses[parent].clone();
+                address.push(*ordinal);
+                *ordinal += 1;
+                trace_addresses[idx] = address;
+            }
+
+            let trace_address = trace_addresses[idx].clone();
+            let mut subtraces = total_subcalls[idx];
+
+            // selfdestructs are not recorded as individual call trace nodes, but are derived from
+            // the call trace exactly as `geth_selfdestruct_call_trace` does for the geth format.
+            let selfdestruct_action = node.parity_selfdestruct_action();
+            if selfdestruct_action.is_some() {
+                subtraces += 1;
+            }
+
+            traces.push(TransactionTrace {
+                action: node.parity_action(),
+                result: node.parity_trace_output(),
+                subtraces,
+                trace_address: trace_address.clone(),
+                error: node.trace.error.clone(),
+            });
+
+            if let Some(action) = selfdestruct_action {
+                let mut address = trace_address;
+                address.push(subtraces - 1);
+                traces.push(TransactionTrace {
+                    action,
+                    result: None,
+                    subtraces: 0,
+                    trace_address: address,
+                    error: None,
+                });
+            }
+        }
+
+        traces
+    }
+
+    /// Computes a 2048-bit address bloom over every address touched by `traces`: `from`/`to` for
+    /// calls, the creator and the resulting address for contract creations, the self-destructed
+    /// account and its beneficiary for selfdestructs, and the author for rewards (see
+    /// [Self::from_to]).
+    ///
+    /// This mirrors the `m3:2048` scheme already used for logs blooms: each address is hashed and
+    /// three bits derived from successive byte pairs of the hash are set, modulo 2048, with all
+    /// touched addresses OR'd together. A downstream `trace_filter` can then call
+    /// [Self::bloom_contains] on a query bloom built the same way to skip any block or
+    /// transaction that cannot possibly match, without inspecting its full traces.
+    pub fn address_bloom(traces: &[TransactionTrace]) -> Bloom {
+        let mut bloom = Bloom::default();
+        for trace in traces {
+            for addr in Self::touched_addresses(trace) {
+                bloom.accrue(BloomInput::Raw(addr.as_bytes()));
+            }
+        }
+        bloom
+    }
+
+    /// Returns `true` only if `bloom` has every bit set that `query` has set, i.e. `bloom` could
+    /// possibly match `query`.
+    pub fn bloom_contains(bloom: &Bloom, query: &Bloom) -> bool {
+        bloom.contains_bloom(query)
+    }
+
+    /// Returns the `(from, to)` addresses relevant to matching and bloom-indexing a single flat
+    /// trace entry: for calls these are the caller/callee; for contract creations, the creator
+    /// and (once executed) the created address; for selfdestructs, the self-destructed account
+    /// and its refund beneficiary; for block rewards, just the author.
+    fn from_to(trace: &TransactionTrace) -> (Option<Address>, Option<Address>) {
+        match &trace.action {
+            Action::Call(call) => (Some(call.from), Some(call.to)),
+            Action::Create(create) => {
+                let to = match &trace.result {
+                    Some(TraceOutput::Create(out)) => Some(out.address),
+                    _ => None,
+                };
+                (Some(create.from), to)
+            }
+            Action::Selfdestruct(selfdestruct) => {
+                (Some(selfdestruct.address), Some(selfdestruct.refund_address))
+            }
+            Action::Reward(reward) => (Some(reward.author), None),
+        }
+    }
+
+    /// Returns every address touched by a single flat trace entry, for [Self::address_bloom].
+    fn touched_addresses(trace: &TransactionTrace) -> Vec<Address> {
+        let (from, to) = Self::from_to(trace);
+        from.into_iter().chain(to).collect()
+    }
+
+    /// Filters `traces` down to those whose `from`/`to` addresses (see [Self::from_to]) are
+    /// present in `from_filter`/`to_filter` (an empty filter matches everything), intended to be
+    /// called once [Self::bloom_contains] has confirmed the containing block/transaction can
+    /// match at all.
+    ///
+    /// Supports pagination over the matches the same way `trace_filter` does: `after` skips the
+    /// first `n` matches and `count` caps how many are returned.
+    pub fn matches<'a>(
+        traces: &'a [TransactionTrace],
+        from_filter: &HashSet<Address>,
+        to_filter: &HashSet<Address>,
+        after: Option<usize>,
+        count: Option<usize>,
+    ) -> Vec<&'a TransactionTrace> {
+        let matches = traces.iter().filter(|trace| {
+            let (from, to) = Self::from_to(trace);
+
+            let from_matches =
+                from_filter.is_empty() || from.map_or(false, |addr| from_filter.contains(&addr));
+            let to_matches =
+                to_filter.is_empty() || to.map_or(false, |addr| to_filter.contains(&addr));
+            from_matches && to_matches
+        });
+
+        matches.skip(after.unwrap_or(0)).take(count.unwrap_or(usize::MAX)).collect()
+    }
+}
+
+impl CallTraceNode {
+    /// Converts this node's call into the equivalent Parity [Action], reusing the same
+    /// underlying call frame data already captured for the geth call tracer format.
+    fn parity_action(&self) -> Action {
+        let call_frame = self.geth_empty_call_frame(false);
+        match call_frame.typ.as_str() {
+            "CREATE" | "CREATE2" => Action::Create(CreateAction {
+                from: call_frame.from,
+                value: call_frame.value.unwrap_or_default(),
+                gas: call_frame.gas.to::<u64>(),
+                init: call_frame.input,
+            }),
+            typ => Action::Call(CallAction {
+                from: call_frame.from,
+                to: call_frame.to.unwrap_or_default(),
+                value: call_frame.value.unwrap_or_default(),
+                gas: call_frame.gas.to::<u64>(),
+                input: call_frame.input,
+                call_type: parity_call_type(typ),
+            }),
+        }
+    }
+
+    /// Converts this node's result into the equivalent Parity [TraceOutput], or `None` if the
+    /// call reverted (Parity traces omit `result` for failed calls, carrying the error instead).
+    fn parity_trace_output(&self) -> Option<TraceOutput> {
+        let call_frame = self.geth_empty_call_frame(false);
+        if call_frame.error.is_some() {
+            return None
+        }
+
+        Some(match call_frame.typ.as_str() {
+            "CREATE" | "CREATE2" => TraceOutput::Create(CreateOutput {
+                gas_used: call_frame.gas_used.to::<u64>(),
+                code: call_frame.output.unwrap_or_default(),
+                address: call_frame.to.unwrap_or_default(),
+            }),
+            _ => TraceOutput::Call(CallOutput {
+                gas_used: call_frame.gas_used.to::<u64>(),
+                output: call_frame.output.unwrap_or_default(),
+            }),
+        })
+    }
+
+    /// Derives this node's synthetic Parity selfdestruct [Action], mirroring
+    /// [Self::geth_selfdestruct_call_trace] for the geth format.
+    fn parity_selfdestruct_action(&self) -> Option<Action> {
+        let selfdestruct: CallFrame = self.geth_selfdestruct_call_trace()?;
+        Some(Action::Selfdestruct(SelfdestructAction {
+            address: selfdestruct.from,
+            refund_address: selfdestruct.to.unwrap_or_default(),
+            balance: selfdestruct.value.unwrap_or_default(),
+        }))
+    }
+}
+
+/// Maps a geth-format call frame `type` string to Parity's [CallType], defaulting to a plain
+/// `Call` for anything else (creations are represented as [Action::Create], not a [CallType]).
+fn parity_call_type(typ: &str) -> CallType {
+    match typ {
+        "STATICCALL" => CallType::StaticCall,
+        "DELEGATECALL" => CallType::DelegateCall,
+        "CALLCODE" => CallType::CallCode,
+        _ => CallType::Call,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_primitives::{Bytes, U256};
+
+    fn call_trace(from: Address, to: Address) -> TransactionTrace {
+        TransactionTrace {
+            action: Action::Call(CallAction {
+                from,
+                to,
+                value: U256::ZERO,
+                gas: 0,
+                input: Bytes::default(),
+                call_type: CallType::Call,
+            }),
+            result: None,
+            subtraces: 0,
+            trace_address: Vec::new(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn address_bloom_contains_touched_addresses_only() {
+        let from = Address::from_low_u64_be(1);
+        let to = Address::from_low_u64_be(2);
+        let traces = vec![call_trace(from, to)];
+
+        let bloom = ParityTraceBuilder::address_bloom(&traces);
+
+        let mut hit = Bloom::default();
+        hit.accrue(BloomInput::Raw(from.as_bytes()));
+        assert!(ParityTraceBuilder::bloom_contains(&bloom, &hit));
+
+        let mut miss = Bloom::default();
+        miss.accrue(BloomInput::Raw(Address::from_low_u64_be(3).as_bytes()));
+        assert!(!ParityTraceBuilder::bloom_contains(&bloom, &miss));
+    }
+
+    #[test]
+    fn matches_respects_to_filter_for_created_contract_address() {
+        let from = Address::from_low_u64_be(1);
+        let created = Address::from_low_u64_be(2);
+        let trace = TransactionTrace {
+            action: Action::Create(CreateAction {
+                from,
+                value: U256::ZERO,
+                gas: 0,
+                init: Bytes::default(),
+            }),
+            result: Some(TraceOutput::Create(CreateOutput {
+                gas_used: 0,
+                code: Bytes::default(),
+                address: created,
+            })),
+            subtraces: 0,
+            trace_address: Vec::new(),
+            error: None,
+        };
+
+        let mut to_filter = HashSet::new();
+        to_filter.insert(created);
+        let matched = ParityTraceBuilder::matches(&[trace], &HashSet::new(), &to_filter, None, None);
+        assert_eq!(matched.len(), 1);
+    }
+
+    #[test]
+    fn matches_respects_to_filter_for_selfdestruct_refund_address() {
+        let address = Address::from_low_u64_be(1);
+        let refund_address = Address::from_low_u64_be(2);
+        let trace = TransactionTrace {
+            action: Action::Selfdestruct(SelfdestructAction {
+                address,
+                refund_address,
+                balance: U256::ZERO,
+            }),
+            result: None,
+            subtraces: 0,
+            trace_address: Vec::new(),
+            error: None,
+        };
+
+        let mut to_filter = HashSet::new();
+        to_filter.insert(refund_address);
+        let matched = ParityTraceBuilder::matches(&[trace], &HashSet::new(), &to_filter, None, None);
+        assert_eq!(matched.len(), 1);
+    }
+
+    #[test]
+    fn matches_filters_by_to_address_and_paginates() {
+        let from = Address::from_low_u64_be(1);
+        let to_a = Address::from_low_u64_be(2);
+        let to_b = Address::from_low_u64_be(3);
+        let traces = vec![call_trace(from, to_a), call_trace(from, to_b)];
+
+        let mut to_filter = HashSet::new();
+        to_filter.insert(to_b);
+        let matched =
+            ParityTraceBuilder::matches(&traces, &HashSet::new(), &to_filter, None, None);
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].action, traces[1].action);
+
+        let paginated =
+            ParityTraceBuilder::matches(&traces, &HashSet::new(), &HashSet::new(), Some(1), Some(1));
+        assert_eq!(paginated.len(), 1);
+        assert_eq!(paginated[0].action, traces[1].action);
+    }
+}
@@ -0,0 +1,49 @@
+//! An [Inspector](revm::Inspector) that records call traces, with builders for converting the
+//! recorded traces into geth and Parity style trace outputs.
+
+pub mod builder;
+pub mod types;
+
+use crate::tracing::{
+    builder::{geth::GethTraceBuilder, parity::ParityTraceBuilder},
+    types::CallTraceNode,
+};
+
+/// Configuration for a [TracingInspector], controlling which data is recorded while a
+/// transaction executes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TracingInspectorConfig {
+    /// Whether to record individual opcode-level steps.
+    pub record_steps: bool,
+    /// Whether to record memory snapshots for each step.
+    pub record_memory_snapshots: bool,
+    /// Whether to record stack snapshots for each step.
+    pub record_stack_snapshots: bool,
+}
+
+/// An inspector that records a [CallTraceNode] for every call frame executed by a transaction.
+///
+/// Once execution has finished, the inspector is consumed and turned into either a
+/// [GethTraceBuilder] or a [ParityTraceBuilder] to render the recorded call trace arena into the
+/// corresponding trace format.
+#[derive(Clone, Debug)]
+pub struct TracingInspector {
+    /// Configuration for the recorded traces.
+    config: TracingInspectorConfig,
+    /// Recorded trace nodes, stored parent-before-child.
+    nodes: Vec<CallTraceNode>,
+}
+
+impl TracingInspector {
+    /// Consumes the inspector and returns a [GethTraceBuilder] for converting the recorded
+    /// traces into geth's trace formats.
+    pub fn into_geth_builder(self) -> GethTraceBuilder {
+        GethTraceBuilder::new(self.nodes, self.config)
+    }
+
+    /// Consumes the inspector and returns a [ParityTraceBuilder] for converting the recorded
+    /// traces into Parity's flat trace format.
+    pub fn into_parity_builder(self) -> ParityTraceBuilder {
+        ParityTraceBuilder::new(self.nodes, self.config)
+    }
+}